@@ -0,0 +1,54 @@
+use std::time::{Duration, Instant};
+
+use crate::utils::ExportBody;
+
+/// Accumulates exported bill receipts until `--max-batch-size` is reached or
+/// `--max-batch-wait-secs` elapses since the first receipt was buffered, so a single
+/// `settle_batch` call amortizes base transaction cost across many claims.
+#[derive(Debug, Default)]
+pub struct BatchBuffer {
+    receipts: Vec<ExportBody>,
+    margin: u128,
+    opened_at: Option<Instant>,
+}
+
+impl BatchBuffer {
+    pub fn push(&mut self, bill_receipt: ExportBody, margin: u128) {
+        if self.receipts.is_empty() {
+            self.opened_at = Some(Instant::now());
+        }
+        self.receipts.push(bill_receipt);
+        self.margin += margin;
+    }
+
+    pub fn len(&self) -> usize {
+        self.receipts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.receipts.is_empty()
+    }
+
+    pub fn margin(&self) -> u128 {
+        self.margin
+    }
+
+    pub fn should_flush(&self, max_batch_size: usize, max_batch_wait: Duration) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+
+        self.receipts.len() >= max_batch_size
+            || self
+                .opened_at
+                .is_some_and(|opened_at| opened_at.elapsed() >= max_batch_wait)
+    }
+
+    pub fn take(&mut self) -> (Vec<ExportBody>, u128) {
+        self.opened_at = None;
+        (
+            std::mem::take(&mut self.receipts),
+            std::mem::take(&mut self.margin),
+        )
+    }
+}
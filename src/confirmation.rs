@@ -0,0 +1,46 @@
+use anyhow::{anyhow, Context, Result};
+use ethers::providers::{Middleware, Provider, Ws};
+use ethers::types::H256;
+use futures_util::StreamExt;
+
+// Reactively awaits confirmation depth over a WebSocket block subscription instead of
+// polling `get_transaction_receipt` once per billing tick, so confirmation latency is no
+// longer bounded by `billing_interval_secs`.
+pub async fn await_confirmation_ws(
+    ws_provider: &Provider<Ws>,
+    tx_hash: H256,
+    confirmation_depth: u64,
+) -> Result<()> {
+    let mut new_blocks = ws_provider
+        .subscribe_blocks()
+        .await
+        .context("Error subscribing to new block headers over the websocket")?;
+
+    while let Some(block) = new_blocks.next().await {
+        let Some(latest_block_number) = block.number else {
+            continue;
+        };
+
+        let receipt = ws_provider
+            .get_transaction_receipt(tx_hash)
+            .await
+            .context(format!(
+                "Error pulling confirmation receipt for the billing transaction {}",
+                tx_hash
+            ))?;
+
+        let Some(receipt) = receipt else { continue };
+        let Some(receipt_block_number) = receipt.block_number else {
+            continue;
+        };
+
+        if latest_block_number.as_u64() >= receipt_block_number.as_u64() + confirmation_depth {
+            return Ok(());
+        }
+    }
+
+    Err(anyhow!(
+        "Websocket block subscription ended before billing transaction {} reached confirmation depth",
+        tx_hash
+    ))
+}
@@ -2,10 +2,31 @@ use std::str::FromStr;
 
 use anyhow::{Context, Result};
 use chrono::Local;
-use ethers::types::{TransactionReceipt, H256};
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{Address, BlockNumber, Eip1559TransactionRequest, TransactionReceipt, H256, U256};
 use ethers::{providers::Middleware, types::Bytes};
 
-use crate::utils::{log_data, BillingContract, ExportBody, SignerClient};
+use crate::utils::{log_data, BillingContract, ExportBody, PendingBillTx, SignerClient};
+
+// `settle()`/`settle_batch()` always build a legacy `TransactionRequest` under the hood (that's
+// what abigen-generated contract calls produce), so `txn.tx` starts out as
+// `TypedTransaction::Legacy` -- rebuild it as EIP-1559 before the fee fields can be set, since
+// `as_eip1559_mut()` on a legacy transaction is always `None` and the fee estimate would
+// otherwise be silently discarded.
+fn upgrade_to_eip1559(tx: &mut TypedTransaction) -> Result<()> {
+    let legacy_tx = tx
+        .as_legacy_mut()
+        .context("Expected the contract call to build a legacy transaction")?;
+    let eip1559_tx = Eip1559TransactionRequest::new()
+        .to(legacy_tx
+            .to
+            .take()
+            .context("Contract call is missing a recipient")?)
+        .data(legacy_tx.data.take().unwrap_or_default())
+        .value(legacy_tx.value.take().unwrap_or_default());
+    *tx = eip1559_tx.into();
+    Ok(())
+}
 
 pub async fn is_confirmation_receipt_pending(
     signer_client: &SignerClient,
@@ -40,11 +61,135 @@ pub async fn is_confirmation_receipt_pending(
     false
 }
 
+// Suggests an EIP-1559 (tip, max fee) pair from the last `fee_history_block_count` blocks,
+// using the median of the requested reward percentile as the priority tip.
+pub async fn suggest_eip1559_fees(
+    signer_client: &SignerClient,
+    fee_history_block_count: u64,
+    fee_reward_percentile: f64,
+    base_fee_multiplier: u64,
+) -> Result<(U256, U256)> {
+    let fee_history = signer_client
+        .fee_history(
+            fee_history_block_count,
+            BlockNumber::Latest,
+            &[fee_reward_percentile],
+        )
+        .await
+        .context("Error fetching fee history for gas price estimation")?;
+
+    let mut rewards: Vec<U256> = fee_history
+        .reward
+        .iter()
+        .filter_map(|block_rewards| block_rewards.first().copied())
+        .collect();
+    rewards.sort();
+
+    let tip = rewards
+        .get(rewards.len() / 2)
+        .copied()
+        .unwrap_or_default();
+
+    let base_fee = *fee_history
+        .base_fee_per_gas
+        .last()
+        .context("Missing base fee in fee history response")?;
+
+    let max_fee = base_fee * base_fee_multiplier + tip;
+
+    Ok((tip, max_fee))
+}
+
+// Fetches the nonce assigned to a freshly submitted billing transaction so it can be
+// tracked (and, if it never confirms, escalated) by that nonce.
+pub async fn track_pending_transaction(
+    signer_client: &SignerClient,
+    tx_hash: H256,
+    bill_receipt: ExportBody,
+) -> Result<PendingBillTx> {
+    let txn = signer_client
+        .get_transaction(tx_hash)
+        .await
+        .context(format!(
+            "Error fetching the submitted billing transaction {}",
+            tx_hash
+        ))?
+        .context(format!(
+            "Submitted billing transaction {} not found on the node",
+            tx_hash
+        ))?;
+
+    Ok(PendingBillTx {
+        nonce: txn.nonce,
+        tx_hash,
+        ticks_pending: 0,
+        bill_receipt,
+    })
+}
+
+// Resubmits a stuck billing transaction at the same nonce with both EIP-1559 fee fields
+// bumped by `fee_bump_percent`, so the replacement is accepted instead of queued behind it.
+pub async fn escalate_pending_transaction(
+    signer_client: &SignerClient,
+    pending: &PendingBillTx,
+    fee_bump_percent: u64,
+) -> Result<H256> {
+    let stuck_txn = signer_client
+        .get_transaction(pending.tx_hash)
+        .await
+        .context(format!(
+            "Error fetching the stuck billing transaction {} for escalation",
+            pending.tx_hash
+        ))?
+        .context(format!(
+            "Stuck billing transaction {} no longer found, cannot escalate",
+            pending.tx_hash
+        ))?;
+
+    let bump = |fee: U256| fee + (fee * fee_bump_percent) / 100;
+    let bumped_priority_fee = bump(stuck_txn.max_priority_fee_per_gas.unwrap_or_default());
+    let bumped_max_fee = bump(stuck_txn.max_fee_per_gas.unwrap_or_default()).max(bumped_priority_fee);
+
+    let replacement = Eip1559TransactionRequest::new()
+        .to(stuck_txn
+            .to
+            .context("Stuck billing transaction has no recipient to replace")?)
+        .data(stuck_txn.input.clone())
+        .value(stuck_txn.value)
+        .nonce(pending.nonce)
+        .max_priority_fee_per_gas(bumped_priority_fee)
+        .max_fee_per_gas(bumped_max_fee);
+
+    let replacement_txn = signer_client
+        .send_transaction(replacement, None)
+        .await
+        .context(format!(
+            "Failed to resubmit escalated billing transaction replacing {}",
+            pending.tx_hash
+        ))?;
+    let replacement_hash = replacement_txn.tx_hash();
+
+    log_data(format!(
+        "[{}] Escalated stuck billing transaction {} -> {} at nonce {} (+{}%)",
+        Local::now().format("%Y-%m-%d %H:%M:%S"),
+        pending.tx_hash,
+        replacement_hash,
+        pending.nonce,
+        fee_bump_percent
+    ));
+
+    Ok(replacement_hash)
+}
+
 pub async fn send_billing_transaction(
     billing_contract: &BillingContract<SignerClient>,
     bill_receipt: &ExportBody,
+    payee: Address,
+    fee_history_block_count: u64,
+    fee_reward_percentile: f64,
+    base_fee_multiplier: u64,
 ) -> Result<(H256, Option<TransactionReceipt>)> {
-    let txn = billing_contract.settle(
+    let mut txn = billing_contract.settle(
         Bytes::from_str(bill_receipt.bill_claim_data.as_str()).context(format!(
             "Failed to parse the bill data {} into ethers Bytes",
             bill_receipt.bill_claim_data
@@ -53,7 +198,23 @@ pub async fn send_billing_transaction(
             "Failed to parse the bill signature {} into ethers Bytes",
             bill_receipt.signature
         ))?,
+        payee,
     ); // parsing errors very unlikely
+    upgrade_to_eip1559(&mut txn.tx)?;
+
+    let (tip, max_fee) = suggest_eip1559_fees(
+        &billing_contract.client(),
+        fee_history_block_count,
+        fee_reward_percentile,
+        base_fee_multiplier,
+    )
+    .await
+    .context("Error estimating EIP-1559 gas fees")?;
+
+    if let Some(eip1559_tx) = txn.tx.as_eip1559_mut() {
+        eip1559_tx.max_priority_fee_per_gas = Some(tip);
+        eip1559_tx.max_fee_per_gas = Some(max_fee);
+    }
 
     let pending_txn = txn.send().await.context(format!(
         "Failed to send the billing transaction for receipt {:?}",
@@ -69,3 +230,130 @@ pub async fn send_billing_transaction(
 
     Ok((bill_tx_hash, bill_tx_receipt))
 }
+
+// Settles several buffered bill receipts in a single `settle_batch` transaction so the base
+// transaction cost is amortized across every claim in the batch rather than paid once per claim.
+//
+// NOTE: `settle_batch` is a new `BillingContract` entrypoint this feature assumes has been
+// deployed alongside it; this crate makes no change to the contract or its ABI. If the deployed
+// contract hasn't actually been upgraded, the call below fails and `send_batched_billing_transaction`
+// falls back to settling each receipt individually through the existing `settle` entrypoint.
+//
+// Returns one `(tx_hash, receipt, bill_receipt)` entry per transaction actually submitted to the
+// network: a single entry covering the whole batch on the happy path, or one entry per receipt
+// when falling back to individual `settle` calls. The caller must track every entry returned --
+// dropping any but the last would silently stop tracking (and escalating) the other settlements.
+pub async fn send_batched_billing_transaction(
+    billing_contract: &BillingContract<SignerClient>,
+    bill_receipts: &[ExportBody],
+    payee: Address,
+    fee_history_block_count: u64,
+    fee_reward_percentile: f64,
+    base_fee_multiplier: u64,
+) -> Result<Vec<(H256, Option<TransactionReceipt>, ExportBody)>> {
+    match try_settle_batch(
+        billing_contract,
+        bill_receipts,
+        payee,
+        fee_history_block_count,
+        fee_reward_percentile,
+        base_fee_multiplier,
+    )
+    .await
+    {
+        Ok((bill_tx_hash, bill_tx_receipt)) => Ok(vec![(
+            bill_tx_hash,
+            bill_tx_receipt,
+            bill_receipts
+                .first()
+                .cloned()
+                .context("Cannot settle an empty batch")?,
+        )]),
+        Err(err) => {
+            log_data(format!(
+                "[{}] settle_batch failed ({}), falling back to settling {} receipt(s) individually -- \
+                 does the deployed BillingContract actually implement settle_batch?",
+                Local::now().format("%Y-%m-%d %H:%M:%S"),
+                err,
+                bill_receipts.len()
+            ));
+
+            let mut submissions = Vec::with_capacity(bill_receipts.len());
+            for bill_receipt in bill_receipts {
+                match send_billing_transaction(
+                    billing_contract,
+                    bill_receipt,
+                    payee,
+                    fee_history_block_count,
+                    fee_reward_percentile,
+                    base_fee_multiplier,
+                )
+                .await
+                {
+                    Ok((tx_hash, receipt)) => submissions.push((tx_hash, receipt, bill_receipt.clone())),
+                    Err(err) => log_data(format!(
+                        "[{}] FATAL ERROR: Lost exported bill receipt pending individual fallback settlement: {}",
+                        Local::now().format("%Y-%m-%d %H:%M:%S"),
+                        err
+                    )),
+                }
+            }
+
+            Ok(submissions)
+        }
+    }
+}
+
+async fn try_settle_batch(
+    billing_contract: &BillingContract<SignerClient>,
+    bill_receipts: &[ExportBody],
+    payee: Address,
+    fee_history_block_count: u64,
+    fee_reward_percentile: f64,
+    base_fee_multiplier: u64,
+) -> Result<(H256, Option<TransactionReceipt>)> {
+    let mut bill_claim_data = Vec::with_capacity(bill_receipts.len());
+    let mut signatures = Vec::with_capacity(bill_receipts.len());
+    for bill_receipt in bill_receipts {
+        bill_claim_data.push(Bytes::from_str(bill_receipt.bill_claim_data.as_str()).context(
+            format!(
+                "Failed to parse the bill data {} into ethers Bytes",
+                bill_receipt.bill_claim_data
+            ),
+        )?);
+        signatures.push(Bytes::from_str(bill_receipt.signature.as_str()).context(format!(
+            "Failed to parse the bill signature {} into ethers Bytes",
+            bill_receipt.signature
+        ))?);
+    }
+
+    let mut txn = billing_contract.settle_batch(bill_claim_data, signatures, payee); // parsing errors very unlikely
+    upgrade_to_eip1559(&mut txn.tx)?;
+
+    let (tip, max_fee) = suggest_eip1559_fees(
+        &billing_contract.client(),
+        fee_history_block_count,
+        fee_reward_percentile,
+        base_fee_multiplier,
+    )
+    .await
+    .context("Error estimating EIP-1559 gas fees")?;
+
+    if let Some(eip1559_tx) = txn.tx.as_eip1559_mut() {
+        eip1559_tx.max_priority_fee_per_gas = Some(tip);
+        eip1559_tx.max_fee_per_gas = Some(max_fee);
+    }
+
+    let pending_txn = txn.send().await.context(format!(
+        "Failed to send the batched billing transaction for {} receipts",
+        bill_receipts.len()
+    ))?;
+    let bill_tx_hash = pending_txn.tx_hash();
+
+    let Ok(bill_tx_receipt) = pending_txn.confirmations(3).await else {
+        // TODO: FIX CONFIRMATIONS
+        return Ok((bill_tx_hash, None));
+    };
+
+    Ok((bill_tx_hash, bill_tx_receipt))
+}
@@ -1,25 +1,41 @@
+mod batch;
+mod confirmation;
 mod contract_calls;
 mod server_calls;
+mod signer;
 mod utils;
 
+use std::collections::HashMap;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::{anyhow, Context, Result};
 use chrono::Local;
 use clap::Parser;
+use ethers::middleware::nonce_manager::NonceManagerMiddleware;
 use ethers::middleware::SignerMiddleware;
-use ethers::providers::{Http, Provider, ProviderExt};
-use ethers::signers::{Signer, Wallet};
-use ethers::types::{Address, H256};
+use ethers::providers::{Http, Middleware, Provider, Quorum, QuorumProvider, WeightedProvider, Ws};
+use ethers::signers::{HDPath, Ledger, Signer, Wallet};
+use ethers::types::{Address, BlockNumber, H256, U256};
 use tokio::fs;
+use tokio::sync::mpsc;
 use tokio::time::{interval, Duration};
 use tokio_retry::strategy::{jitter, ExponentialBackoff};
 use tokio_retry::Retry;
 
-use contract_calls::{is_confirmation_receipt_pending, send_billing_transaction};
+use batch::BatchBuffer;
+use confirmation::await_confirmation_ws;
+use contract_calls::{
+    escalate_pending_transaction, is_confirmation_receipt_pending, send_batched_billing_transaction,
+    send_billing_transaction, track_pending_transaction,
+};
 use server_calls::{fetch_bill_receipt, fetch_current_bill, fetch_last_bill_receipt};
-use utils::{is_valid_ip_with_port, BillingContract, ExportBody, SignerClient};
+use signer::{SignerSource, SignerType};
+use utils::{
+    is_valid_ip_with_port, load_nonce_manager_state, persist_nonce_manager_state, BillingContract,
+    ExportBody, NonceManagerState, PendingBillTx, SignerClient, NONCE_STATE_PATH,
+};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -27,12 +43,24 @@ struct CliArgs {
     #[clap(long, value_parser)]
     id: u32,
 
+    /// RPC endpoints; comma-separated. Every call -- reads AND writes, including settlement and
+    /// escalation transactions -- is broadcast to all of them simultaneously via a quorum
+    /// provider, not tried one at a time with sequential fallback. With the default threshold of
+    /// 1 this degrades to "first endpoint to answer wins", which is cheap and fast but still
+    /// means N endpoints receive every `eth_sendRawTransaction`, not just the one that responds.
     #[clap(
         long,
         value_parser,
+        value_delimiter = ',',
         default_value = "https://sepolia-rollup.arbitrum.io/rpc"
     )]
-    rpc_url: String,
+    rpc_urls: Vec<String>,
+
+    /// Number of RPC endpoints that must return a matching response before it's trusted; raising
+    /// this above 1 increases confidence in reads (e.g. a receipt) at the cost of broadcasting
+    /// every write to more endpoints and waiting longer for them to agree
+    #[clap(long, value_parser, default_value = "1")]
+    rpc_quorum_threshold: u64,
 
     #[clap(long, value_parser)]
     billing_ip_port: String,
@@ -40,9 +68,22 @@ struct CliArgs {
     #[clap(long, value_parser)]
     billing_contract_addr: String,
 
+    /// Path to the raw hex key file or the encrypted JSON keystore, depending on --signer-type
     #[clap(long, value_parser)]
     secret_key_file: String,
 
+    /// Which key material --secret-key-file (or the Ledger device) provides
+    #[clap(long, value_enum, default_value = "raw-key")]
+    signer_type: SignerType,
+
+    /// Env var holding the keystore passphrase; required when --signer-type=keystore
+    #[clap(long, value_parser)]
+    keystore_password_env: Option<String>,
+
+    /// Ledger Live derivation path index; used when --signer-type=ledger
+    #[clap(long, value_parser, default_value = "0")]
+    ledger_derivation_index: usize,
+
     #[clap(long, value_parser)]
     payee_wallet_address: String,
 
@@ -54,6 +95,45 @@ struct CliArgs {
 
     #[clap(long, value_parser, default_value = "")] // TODO: DEFAULT VALUE
     billing_interval_secs: u64,
+
+    /// Number of trailing blocks to sample when estimating EIP-1559 priority fees
+    #[clap(long, value_parser, default_value = "10")]
+    fee_history_block_count: u64,
+
+    /// Reward percentile requested from eth_feeHistory to derive the suggested priority tip
+    #[clap(long, value_parser, default_value = "50.0")]
+    fee_reward_percentile: f64,
+
+    /// Multiplier applied to the latest base fee when computing max_fee_per_gas
+    #[clap(long, value_parser, default_value = "2")]
+    base_fee_multiplier: u64,
+
+    /// Number of billing ticks a settlement transaction may stay unconfirmed before it's escalated
+    #[clap(long, value_parser, default_value = "3")]
+    escalate_after_ticks: u64,
+
+    /// Percentage bump applied to both EIP-1559 fee fields on each escalation (min replacement bump is 12.5%)
+    #[clap(long, value_parser, default_value = "13")]
+    fee_bump_percent: u64,
+
+    /// Optional websocket RPC endpoint; when set, confirmations are awaited reactively over a
+    /// block-header subscription instead of polling once per billing tick
+    #[clap(long, value_parser)]
+    ws_rpc_url: Option<String>,
+
+    /// Number of confirming blocks required before a websocket-watched settlement is considered final
+    #[clap(long, value_parser, default_value = "3")]
+    confirmation_depth: u64,
+
+    /// Number of exported bill receipts to accumulate before settling them in a single batched
+    /// transaction; with the default of 1 each receipt is settled on the tick it is exported, as
+    /// long as the buffered margin exceeds --method-call-cost (otherwise it stays buffered)
+    #[clap(long, value_parser, default_value = "1")]
+    max_batch_size: usize,
+
+    /// Maximum time a receipt may sit in the batch buffer before it's flushed regardless of size
+    #[clap(long, value_parser, default_value = "30")]
+    max_batch_wait_secs: u64,
 }
 
 async fn biller(
@@ -65,7 +145,13 @@ async fn biller(
     balance_transfer_cost: u128,
     nonce: &mut [u8],
     payee: Address,
-) -> (bool, Option<ExportBody>, Option<H256>) {
+    fee_history_block_count: u64,
+    fee_reward_percentile: f64,
+    base_fee_multiplier: u64,
+    batch: &mut BatchBuffer,
+    max_batch_size: usize,
+    max_batch_wait: Duration,
+) -> (bool, Option<ExportBody>, Vec<(H256, ExportBody)>) {
     if is_last_bill_exported {
         let last_bill_receipt = match bill_receipt {
             Some(bill_receipt) => bill_receipt,
@@ -83,7 +169,7 @@ async fn biller(
                         Local::now().format("%Y-%m-%d %H:%M:%S"),
                         bill_receipt.unwrap_err()
                     );
-                    return (true, None, None);
+                    return (true, None, Vec::new());
                 };
 
                 let Some(bill_receipt) = bill_receipt else {
@@ -91,16 +177,23 @@ async fn biller(
                         "[{}] FATAL ERROR: Lost exported bill info pending to claim!!!",
                         Local::now().format("%Y-%m-%d %H:%M:%S")
                     );
-                    return (false, None, None);
+                    return (false, None, Vec::new());
                 };
 
                 bill_receipt
             }
         };
 
-        let billing_tx = send_billing_transaction(billing_contract, &last_bill_receipt, payee)
-            .await
-            .context("Error sending the billing transaction to the network");
+        let billing_tx = send_billing_transaction(
+            billing_contract,
+            &last_bill_receipt,
+            payee,
+            fee_history_block_count,
+            fee_reward_percentile,
+            base_fee_multiplier,
+        )
+        .await
+        .context("Error sending the billing transaction to the network");
 
         let Ok((bill_tx_hash, bill_tx_receipt)) = billing_tx else {
             eprintln!(
@@ -108,7 +201,7 @@ async fn biller(
                 Local::now().format("%Y-%m-%d %H:%M:%S"),
                 billing_tx.unwrap_err()
             );
-            return (true, Some(last_bill_receipt), None);
+            return (true, Some(last_bill_receipt), Vec::new());
         };
 
         let Some(bill_tx_receipt) = bill_tx_receipt else {
@@ -117,7 +210,7 @@ async fn biller(
                 Local::now().format("%Y-%m-%d %H:%M:%S"),
                 bill_tx_hash
             );
-            return (false, None, Some(bill_tx_hash));
+            return (false, None, vec![(bill_tx_hash, last_bill_receipt)]);
         };
 
         println!(
@@ -141,7 +234,7 @@ async fn biller(
             Local::now().format("%Y-%m-%d %H:%M:%S"),
             current_bill.unwrap_err()
         );
-        return (false, None, None);
+        return (false, None, Vec::new());
     };
 
     let mut exporting_tx_hashes = Vec::new();
@@ -153,12 +246,12 @@ async fn biller(
         }
     }
 
-    if exporting_tx_hashes.is_empty() || margin <= method_call_cost {
+    if exporting_tx_hashes.is_empty() {
         println!(
             "[{}] Bill isn't worth claiming!!!",
             Local::now().format("%Y-%m-%d %H:%M:%S")
         );
-        return (false, None, None);
+        return (false, None, Vec::new());
     }
 
     let timestamp = SystemTime::now()
@@ -171,7 +264,7 @@ async fn biller(
             Local::now().format("%Y-%m-%d %H:%M:%S"),
             timestamp.unwrap_err()
         );
-        return (false, None, None);
+        return (false, None, Vec::new());
     };
 
     nonce[24..].copy_from_slice(&timestamp.as_secs().to_be_bytes());
@@ -192,43 +285,90 @@ async fn biller(
             Local::now().format("%Y-%m-%d %H:%M:%S"),
             bill_receipt.unwrap_err()
         );
-        return (false, None, None);
+        return (false, None, Vec::new());
     };
 
     let Some(bill_receipt) = bill_receipt else {
-        return (true, None, None);
+        return (true, None, Vec::new());
     };
 
-    let bill_tx = send_billing_transaction(billing_contract, &bill_receipt, payee)
-        .await
-        .context("Error sending the billing transaction to the network");
+    batch.push(bill_receipt, margin);
 
-    let Ok((bill_tx_hash, bill_tx_receipt)) = bill_tx else {
-        eprintln!(
-            "[{}] {}",
+    if !batch.should_flush(max_batch_size, max_batch_wait) {
+        println!(
+            "[{}] Buffered bill receipt for batched settlement ({}/{})",
             Local::now().format("%Y-%m-%d %H:%M:%S"),
-            bill_tx.unwrap_err()
+            batch.len(),
+            max_batch_size
         );
-        return (true, Some(bill_receipt), None);
-    };
+        return (false, None, Vec::new());
+    }
 
-    let Some(bill_tx_receipt) = bill_tx_receipt else {
+    if batch.margin() <= method_call_cost {
+        // Unprofitable for now, but not lost: leave the receipts buffered so they keep
+        // accumulating (past --max-batch-size if needed) until the batch is worth claiming,
+        // instead of discarding them here.
         println!(
-            "[{}] Bill submitted {}, PENDING confirmation receipt!!!",
+            "[{}] Batch isn't worth claiming yet (margin {} <= cost {}), holding {} buffered receipt(s)",
+            Local::now().format("%Y-%m-%d %H:%M:%S"),
+            batch.margin(),
+            method_call_cost,
+            batch.len()
+        );
+        return (false, None, Vec::new());
+    }
+
+    let (batched_receipts, _batched_margin) = batch.take();
+    let batched_len = batched_receipts.len();
+
+    let submissions = send_batched_billing_transaction(
+        billing_contract,
+        &batched_receipts,
+        payee,
+        fee_history_block_count,
+        fee_reward_percentile,
+        base_fee_multiplier,
+    )
+    .await;
+
+    let Ok(submissions) = submissions else {
+        eprintln!(
+            "[{}] {}",
+            Local::now().format("%Y-%m-%d %H:%M:%S"),
+            submissions.unwrap_err()
+        );
+        eprintln!(
+            "[{}] FATAL ERROR: Lost {} exported bill receipt(s) pending batched claim!!!",
             Local::now().format("%Y-%m-%d %H:%M:%S"),
-            bill_tx_hash
+            batched_len
         );
-        return (false, None, Some(bill_tx_hash));
+        return (false, None, Vec::new());
     };
 
-    println!(
-        "[{}] Bill submitted {} successfully with confirmation receipt: {:?}",
-        Local::now().format("%Y-%m-%d %H:%M:%S"),
-        bill_tx_hash,
-        bill_tx_receipt
-    );
+    // One settlement tx can cover the whole batch (the happy path) or, after a fallback to
+    // per-receipt `settle`, one tx per receipt -- track every hash that was actually submitted
+    // so none of them are lost to escalation/resumption.
+    let mut still_pending = Vec::new();
+    for (bill_tx_hash, bill_tx_receipt, bill_receipt) in submissions {
+        match bill_tx_receipt {
+            Some(bill_tx_receipt) => println!(
+                "[{}] Batched bill submitted {} successfully with confirmation receipt: {:?}",
+                Local::now().format("%Y-%m-%d %H:%M:%S"),
+                bill_tx_hash,
+                bill_tx_receipt
+            ),
+            None => {
+                println!(
+                    "[{}] Batched bill submitted {}, PENDING confirmation receipt!!!",
+                    Local::now().format("%Y-%m-%d %H:%M:%S"),
+                    bill_tx_hash
+                );
+                still_pending.push((bill_tx_hash, bill_receipt));
+            }
+        }
+    }
 
-    (false, None, None)
+    (false, None, still_pending)
 }
 
 #[tokio::main]
@@ -242,23 +382,66 @@ async fn main() -> Result<()> {
         ));
     }
 
-    let rpc_provider = Provider::<Http>::try_connect(&cli.rpc_url)
-        .await
-        .context(format!("Error connecting to the rpc {}", cli.rpc_url))?;
-    let signer_wallet = Wallet::from_bytes(
-        hex::decode(
-            fs::read_to_string(&cli.secret_key_file)
+    let rpc_providers = cli
+        .rpc_urls
+        .iter()
+        .map(|rpc_url| {
+            Http::from_str(rpc_url)
+                .context(format!("Error parsing the rpc url {}", rpc_url))
+                .map(WeightedProvider::new)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let quorum_provider = QuorumProvider::builder()
+        .add_providers(rpc_providers)
+        .quorum(Quorum::ProviderCount(cli.rpc_quorum_threshold as usize))
+        .build();
+    let rpc_provider = Provider::new(quorum_provider);
+    let signer_source = match cli.signer_type {
+        SignerType::RawKey => {
+            let wallet = Wallet::from_bytes(
+                hex::decode(
+                    fs::read_to_string(&cli.secret_key_file)
+                        .await
+                        .context(format!(
+                            "Error reading the secret key file at path {}",
+                            cli.secret_key_file
+                        ))?,
+                )
+                .context("Error decoding the secret key")?
+                .as_slice(),
+            )
+            .context("Invalid secret key provided")?;
+            SignerSource::Local(wallet)
+        }
+        SignerType::Keystore => {
+            let password_env = cli.keystore_password_env.as_deref().context(
+                "Error: --keystore-password-env is required when --signer-type is keystore",
+            )?;
+            let password = std::env::var(password_env).context(format!(
+                "Error reading the keystore passphrase from env var {}",
+                password_env
+            ))?;
+            let wallet = Wallet::decrypt_keystore(&cli.secret_key_file, password).context(
+                format!("Error decrypting the keystore at {}", cli.secret_key_file),
+            )?;
+            SignerSource::Local(wallet)
+        }
+        SignerType::Ledger => {
+            // The Ledger uses this chain id both to fill in an unset tx chain id and to compute
+            // the EIP-155 `v` value, so it must be the real network id, not `--id` (which is just
+            // an operator/instance identifier folded into the export nonce below).
+            let chain_id = rpc_provider
+                .get_chainid()
                 .await
-                .context(format!(
-                    "Error reading the secret key file at path {}",
-                    cli.secret_key_file
-                ))?,
-        )
-        .context("Error decoding the secret key")?
-        .as_slice(),
-    )
-    .context("Invalid secret key provided")?;
-    let wallet_address = signer_wallet.address();
+                .context("Error fetching the chain id from the rpc provider")?
+                .as_u64();
+            let ledger = Ledger::new(HDPath::LedgerLive(cli.ledger_derivation_index), chain_id)
+                .await
+                .context("Error connecting to the Ledger hardware wallet")?;
+            SignerSource::Ledger(Arc::new(ledger))
+        }
+    };
+    let wallet_address = signer_source.address();
     let payee_wallet_address = cli
         .payee_wallet_address
         .parse::<Address>()
@@ -267,7 +450,26 @@ async fn main() -> Result<()> {
             cli.payee_wallet_address
         ))?;
 
-    let signer_client = SignerMiddleware::new(rpc_provider, signer_wallet);
+    let signer_middleware = SignerMiddleware::new(rpc_provider, signer_source);
+    // `NonceManagerMiddleware` isn't `Clone` (it owns an `AtomicU64` nonce counter that must
+    // stay single-instance), so it's wrapped in an `Arc` once here and shared via `Arc::clone`
+    // everywhere a handle to it is needed, instead of cloning the middleware itself.
+    let signer_client = Arc::new(NonceManagerMiddleware::new(signer_middleware, wallet_address));
+    let nonce_manager_state = load_nonce_manager_state(NONCE_STATE_PATH);
+    let mut current_nonce = signer_client
+        .initialize_nonce(Some(BlockNumber::Pending.into()))
+        .await
+        .context("Error initializing the nonce manager from the account's pending nonce")?;
+    // `initialize_nonce` only accepts a block to query, not a nonce to seed with -- there's no
+    // setter for the manager's internal counter, so if the persisted state remembers a higher
+    // in-flight nonce than the node just reported (e.g. the node hasn't seen our last broadcast
+    // yet), fast-forward past it by burning through `.next()` calls before any real use.
+    if let Some(last_nonce) = nonce_manager_state.last_nonce {
+        let next_nonce = last_nonce + U256::one();
+        while current_nonce < next_nonce {
+            current_nonce = signer_client.next() + U256::one();
+        }
+    }
     let billing_contract = BillingContract::new(
         cli.billing_contract_addr
             .parse::<Address>()
@@ -275,44 +477,197 @@ async fn main() -> Result<()> {
                 "Error parsing the billing contract address {} to eth bytes",
                 cli.billing_contract_addr
             ))?,
-        Arc::new(signer_client.clone()),
+        Arc::clone(&signer_client),
     );
 
+    let ws_provider: Option<Provider<Ws>> = match &cli.ws_rpc_url {
+        Some(ws_rpc_url) => Some(
+            Provider::<Ws>::connect(ws_rpc_url)
+                .await
+                .context(format!("Error connecting to the websocket rpc {}", ws_rpc_url))?,
+        ),
+        None => None,
+    };
+    let (confirmed_nonce_tx, mut confirmed_nonce_rx) = mpsc::unbounded_channel::<U256>();
+
     let mut nonce = [0u8; 32];
     nonce[..20].copy_from_slice(wallet_address.as_bytes());
     nonce[20..24].copy_from_slice(&cli.id.to_be_bytes());
 
     let mut is_last_bill_exported = false;
     let mut bill_receipt: Option<ExportBody> = None;
-    let mut pending_bill_tx_hashes: Vec<H256> = Vec::new();
+    let mut pending_bill_txs: HashMap<U256, PendingBillTx> = nonce_manager_state
+        .in_flight
+        .into_iter()
+        .map(|pending| (pending.nonce, pending))
+        .collect();
+    if !pending_bill_txs.is_empty() {
+        println!(
+            "[{}] Resuming {} in-flight settlement(s) from a previous run",
+            Local::now().format("%Y-%m-%d %H:%M:%S"),
+            pending_bill_txs.len()
+        );
+    }
     let mut interval = interval(Duration::from_secs(cli.billing_interval_secs));
+    let mut batch = BatchBuffer::default();
+    let max_batch_wait = Duration::from_secs(cli.max_batch_wait_secs);
+    // One reactive watcher task per nonce submitted by this run; re-spawned (and the superseded
+    // watcher aborted) whenever that nonce's transaction is escalated, so no task is ever left
+    // polling a hash that can no longer confirm. Settlements resumed from a previous run get a
+    // watcher only once they are escalated; until then the polling path confirms them.
+    let mut ws_watchers: HashMap<U256, tokio::task::JoinHandle<()>> = HashMap::new();
 
     loop {
-        interval.tick().await;
+        tokio::select! {
+            // Reactive path: a websocket watcher reached confirmation depth before the next tick.
+            Some(confirmed_nonce) = confirmed_nonce_rx.recv() => {
+                if pending_bill_txs.remove(&confirmed_nonce).is_some() {
+                    println!(
+                        "[{}] Billing transaction at nonce {} confirmed reactively over websocket",
+                        Local::now().format("%Y-%m-%d %H:%M:%S"),
+                        confirmed_nonce
+                    );
+                    persist_pending_bill_txs(&pending_bill_txs);
+                }
+                if let Some(watcher) = ws_watchers.remove(&confirmed_nonce) {
+                    watcher.abort();
+                }
+            }
 
-        let mut updated_pending_bills: Vec<H256> = Vec::new();
-        for tx_hash in pending_bill_tx_hashes {
-            if is_confirmation_receipt_pending(&signer_client, tx_hash).await {
-                updated_pending_bills.push(tx_hash);
+            // Polling path: the regular billing tick, also the only path when no websocket is set.
+            _ = interval.tick() => {
+                let mut still_pending: HashMap<U256, PendingBillTx> = HashMap::new();
+                for (tx_nonce, mut pending) in pending_bill_txs {
+                    if !is_confirmation_receipt_pending(&signer_client, pending.tx_hash).await {
+                        if let Some(watcher) = ws_watchers.remove(&tx_nonce) {
+                            watcher.abort();
+                        }
+                        continue;
+                    }
+
+                    pending.ticks_pending += 1;
+                    if pending.ticks_pending >= cli.escalate_after_ticks {
+                        match escalate_pending_transaction(
+                            &signer_client,
+                            &pending,
+                            cli.fee_bump_percent,
+                        )
+                        .await
+                        {
+                            Ok(replacement_hash) => {
+                                pending.tx_hash = replacement_hash;
+                                pending.ticks_pending = 0;
+
+                                if let Some(watcher) = ws_watchers.remove(&tx_nonce) {
+                                    watcher.abort();
+                                }
+                                if let Some(ws_provider) = ws_provider.clone() {
+                                    ws_watchers.insert(
+                                        tx_nonce,
+                                        spawn_ws_watcher(
+                                            ws_provider,
+                                            confirmed_nonce_tx.clone(),
+                                            cli.confirmation_depth,
+                                            tx_nonce,
+                                            replacement_hash,
+                                        ),
+                                    );
+                                }
+                            }
+                            Err(err) => eprintln!(
+                                "[{}] {}",
+                                Local::now().format("%Y-%m-%d %H:%M:%S"),
+                                err
+                            ),
+                        }
+                    }
+
+                    still_pending.insert(tx_nonce, pending);
+                }
+                pending_bill_txs = still_pending;
+                persist_pending_bill_txs(&pending_bill_txs);
+
+                let mut _bill_tx_hashes = Vec::new();
+                (is_last_bill_exported, bill_receipt, _bill_tx_hashes) = biller(
+                    is_last_bill_exported,
+                    bill_receipt,
+                    &cli.billing_ip_port,
+                    &billing_contract,
+                    cli.method_call_cost,
+                    cli.balance_transfer_cost,
+                    nonce.as_mut_slice(),
+                    payee_wallet_address,
+                    cli.fee_history_block_count,
+                    cli.fee_reward_percentile,
+                    cli.base_fee_multiplier,
+                    &mut batch,
+                    cli.max_batch_size,
+                    max_batch_wait,
+                )
+                .await;
+
+                for (bill_tx_hash, bill_receipt) in _bill_tx_hashes {
+                    match track_pending_transaction(&signer_client, bill_tx_hash, bill_receipt).await {
+                        Ok(pending) => {
+                            let tracked_nonce = pending.nonce;
+                            pending_bill_txs.insert(tracked_nonce, pending);
+                            persist_pending_bill_txs(&pending_bill_txs);
+
+                            if let Some(ws_provider) = ws_provider.clone() {
+                                ws_watchers.insert(
+                                    tracked_nonce,
+                                    spawn_ws_watcher(
+                                        ws_provider,
+                                        confirmed_nonce_tx.clone(),
+                                        cli.confirmation_depth,
+                                        tracked_nonce,
+                                        bill_tx_hash,
+                                    ),
+                                );
+                            }
+                        }
+                        Err(err) => eprintln!(
+                            "[{}] {}",
+                            Local::now().format("%Y-%m-%d %H:%M:%S"),
+                            err
+                        ),
+                    }
+                }
             }
         }
-        pending_bill_tx_hashes = updated_pending_bills;
-
-        let mut _bill_tx_hash = None;
-        (is_last_bill_exported, bill_receipt, _bill_tx_hash) = biller(
-            is_last_bill_exported,
-            bill_receipt,
-            &cli.billing_ip_port,
-            &billing_contract,
-            cli.method_call_cost,
-            cli.balance_transfer_cost,
-            nonce.as_mut_slice(),
-            payee_wallet_address,
-        )
-        .await;
+    }
+}
 
-        if let Some(bill_tx_hash) = _bill_tx_hash {
-            pending_bill_tx_hashes.push(bill_tx_hash);
+// Spawns the reactive websocket watcher for a single in-flight nonce. The caller is
+// responsible for aborting the previously spawned watcher (if any) for that nonce before
+// replacing it, so an escalated transaction never leaves a stale watcher polling a hash that
+// will never confirm.
+fn spawn_ws_watcher(
+    ws_provider: Provider<Ws>,
+    confirmed_nonce_tx: mpsc::UnboundedSender<U256>,
+    confirmation_depth: u64,
+    nonce: U256,
+    tx_hash: H256,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        if await_confirmation_ws(&ws_provider, tx_hash, confirmation_depth)
+            .await
+            .is_ok()
+        {
+            let _ = confirmed_nonce_tx.send(nonce);
         }
+    })
+}
+
+// Snapshots the outstanding settlements to `NONCE_STATE_PATH` so a restarted biller can
+// reload and resume them instead of hitting the "Lost exported bill info" fatal path.
+fn persist_pending_bill_txs(pending_bill_txs: &HashMap<U256, PendingBillTx>) {
+    let state = NonceManagerState {
+        last_nonce: pending_bill_txs.keys().max().copied(),
+        in_flight: pending_bill_txs.values().cloned().collect(),
+    };
+
+    if let Err(err) = persist_nonce_manager_state(NONCE_STATE_PATH, &state) {
+        eprintln!("[{}] {}", Local::now().format("%Y-%m-%d %H:%M:%S"), err);
     }
 }
@@ -0,0 +1,104 @@
+use std::fmt;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use ethers::signers::{Ledger, LocalWallet, Signer};
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::transaction::eip712::Eip712;
+use ethers::types::{Address, Signature};
+
+/// Which key material `--signer-type` loads: an unencrypted hex file, a passphrase-protected
+/// JSON keystore (both resolve to a `LocalWallet`), or a Ledger hardware wallet.
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum SignerType {
+    RawKey,
+    Keystore,
+    Ledger,
+}
+
+/// Unifies the raw-key, encrypted-keystore, and Ledger signing paths behind one `Signer` impl
+/// so `SignerMiddleware` and `send_billing_transaction` stay oblivious to which one is active.
+/// This keeps an unencrypted key off disk inside the enclave host when `Ledger` is used.
+#[derive(Clone, Debug)]
+pub enum SignerSource {
+    Local(LocalWallet),
+    Ledger(Arc<Ledger>),
+}
+
+#[derive(Debug)]
+pub struct SignerSourceError(Box<dyn std::error::Error + Send + Sync>);
+
+impl fmt::Display for SignerSourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for SignerSourceError {}
+
+impl From<ethers::signers::WalletError> for SignerSourceError {
+    fn from(err: ethers::signers::WalletError) -> Self {
+        SignerSourceError(Box::new(err))
+    }
+}
+
+impl From<ethers::signers::LedgerError> for SignerSourceError {
+    fn from(err: ethers::signers::LedgerError) -> Self {
+        SignerSourceError(Box::new(err))
+    }
+}
+
+#[async_trait]
+impl Signer for SignerSource {
+    type Error = SignerSourceError;
+
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
+    ) -> Result<Signature, Self::Error> {
+        match self {
+            SignerSource::Local(wallet) => Ok(wallet.sign_message(message).await?),
+            SignerSource::Ledger(ledger) => Ok(ledger.sign_message(message).await?),
+        }
+    }
+
+    async fn sign_transaction(&self, message: &TypedTransaction) -> Result<Signature, Self::Error> {
+        match self {
+            SignerSource::Local(wallet) => Ok(wallet.sign_transaction(message).await?),
+            SignerSource::Ledger(ledger) => Ok(ledger.sign_transaction(message).await?),
+        }
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(
+        &self,
+        payload: &T,
+    ) -> Result<Signature, Self::Error> {
+        match self {
+            SignerSource::Local(wallet) => Ok(wallet.sign_typed_data(payload).await?),
+            SignerSource::Ledger(ledger) => Ok(ledger.sign_typed_data(payload).await?),
+        }
+    }
+
+    fn address(&self) -> Address {
+        match self {
+            SignerSource::Local(wallet) => wallet.address(),
+            SignerSource::Ledger(ledger) => ledger.address(),
+        }
+    }
+
+    fn chain_id(&self) -> u64 {
+        match self {
+            SignerSource::Local(wallet) => wallet.chain_id(),
+            SignerSource::Ledger(ledger) => ledger.chain_id(),
+        }
+    }
+
+    fn with_chain_id<T: Into<u64>>(self, chain_id: T) -> Self {
+        match self {
+            SignerSource::Local(wallet) => SignerSource::Local(wallet.with_chain_id(chain_id)),
+            // The shared Ledger keeps the chain id it was connected with; the requested chain
+            // id is not applied to it.
+            SignerSource::Ledger(ledger) => SignerSource::Ledger(ledger),
+        }
+    }
+}
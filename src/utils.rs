@@ -3,16 +3,63 @@ use std::fs::OpenOptions;
 use std::io::Write;
 use std::net::ToSocketAddrs;
 
-use anyhow::Context;
+use anyhow::{Context, Result};
 use chrono::Local;
 use ethers::contract::abigen;
+use ethers::middleware::nonce_manager::NonceManagerMiddleware;
 use ethers::middleware::SignerMiddleware;
-use ethers::providers::{Http, Provider};
-use ethers::signers::Wallet;
-use k256::ecdsa::SigningKey;
+use ethers::providers::{Http, Provider, QuorumProvider};
+use ethers::types::{H256, U256};
 use serde::{Deserialize, Serialize};
 
-pub type SignerClient = SignerMiddleware<Provider<Http>, Wallet<SigningKey>>;
+use crate::signer::SignerSource;
+
+/// The RPC transport: a quorum of `Http` endpoints so a single flaky node can't stall billing.
+/// Every request (reads and writes alike, including settlement/escalation transactions) is
+/// broadcast to all configured endpoints simultaneously, not tried sequentially with fallback --
+/// with `--rpc-quorum-threshold 1` this degenerates to "first endpoint to answer wins", but every
+/// endpoint still receives every call.
+pub type SignerClient =
+    NonceManagerMiddleware<SignerMiddleware<Provider<QuorumProvider<Http>>, SignerSource>>;
+
+/// Path, next to `logs.log`, where the nonce manager's durable state is kept so a restarted
+/// biller can resume escalating/confirming settlements instead of losing track of them.
+pub const NONCE_STATE_PATH: &str = "nonce_state.json";
+
+/// A billing settlement transaction that hasn't confirmed yet, tracked by the nonce it
+/// occupies so a fee-escalated replacement naturally supersedes the hash it was tracked under.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingBillTx {
+    pub nonce: U256,
+    pub tx_hash: H256,
+    pub ticks_pending: u64,
+    pub bill_receipt: ExportBody,
+}
+
+/// Durable snapshot of the nonce manager's outstanding work, reloaded on startup so a crashed
+/// biller resumes its in-flight settlements instead of hitting `FATAL ERROR: Lost exported bill
+/// info pending to claim!!!`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NonceManagerState {
+    pub last_nonce: Option<U256>,
+    pub in_flight: Vec<PendingBillTx>,
+}
+
+pub fn load_nonce_manager_state(path: &str) -> NonceManagerState {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+pub fn persist_nonce_manager_state(path: &str, state: &NonceManagerState) -> Result<()> {
+    let contents =
+        serde_json::to_string_pretty(state).context("Error serializing nonce manager state")?;
+    std::fs::write(path, contents).context(format!(
+        "Error persisting nonce manager state to {}",
+        path
+    ))
+}
 
 abigen!(
     BillingContract,
@@ -25,7 +72,7 @@ pub struct InspectBody {
     pub bill: HashMap<String, u128>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExportBody {
     pub bill_claim_data: String,
     pub signature: String,